@@ -1,7 +1,13 @@
+extern crate bincode;
 extern crate itertools;
+extern crate rayon;
 extern crate serde;
+#[macro_use]
+extern crate serde_derive;
+extern crate sha3;
 extern crate treebitmap;
 
+mod mrt;
 mod view;
 mod world;
 
@@ -10,15 +16,16 @@ use world::World;
 
 use itertools::Itertools;
 use std::collections::HashMap;
+use std::env;
 use std::fs::File;
 use std::io::{BufRead, BufReader};
 use std::net::IpAddr;
-use std::rc::Rc;
 use std::str::FromStr;
+use std::sync::Arc;
 
 
 
-fn load_views(fname: &str, world : Rc<World>) -> HashMap<String, View> {
+fn load_views(fname: &str, world : Arc<World>) -> HashMap<String, View> {
     let mut result = HashMap::new();
     let f = File::open(fname).expect("file not found");
     for line in BufReader::new(f).lines().map(|x| x.unwrap()) {
@@ -32,9 +39,20 @@ fn load_views(fname: &str, world : Rc<World>) -> HashMap<String, View> {
 }
 
 fn main() {
-    let w = World::build_new("../as_relationships.txt", "../bgp.txt", "../sites.txt");
-    let rc_w = Rc::new(w);
-    let views = load_views("../servers.txt", rc_w);
+    if let Ok(num_threads) = env::var("WORLDVIEW_THREADS").map(|v| v.parse::<usize>()) {
+        let num_threads = num_threads.expect("WORLDVIEW_THREADS must be a positive integer");
+        assert!(num_threads > 0, "WORLDVIEW_THREADS must be a positive integer");
+        View::configure_thread_pool(num_threads);
+    }
+
+    let w = World::build_or_load(
+        "../world.cache",
+        "../as_relationships.txt",
+        "../bgp.txt",
+        "../sites.txt",
+    );
+    let arc_w = Arc::new(w);
+    let views = load_views("../servers.txt", arc_w);
     
     println!("{}", views.len());
    
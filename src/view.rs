@@ -1,20 +1,23 @@
 use std::collections::hash_map::Entry::{Occupied, Vacant};
 use std::collections::{HashMap, HashSet};
-use std::rc::Rc;
+use std::sync::Arc;
+
+use rayon::prelude::*;
+use rayon::ThreadPoolBuilder;
 
 use std::net::IpAddr;
 use world::{Path, World, ASN};
 
 #[derive(Clone)]
 pub struct View {
-    world: Rc<World>,
+    world: Arc<World>,
     perspectives: Vec<IpAddr>,
     hard_core: HashMap<(IpAddr, u32), HashSet<ASN>>,
     all_seen: HashMap<(IpAddr, u32), HashSet<ASN>>,
 }
 
 impl View {
-    pub fn new(world: Rc<World>) -> Self {
+    pub fn new(world: Arc<World>) -> Self {
         View {
             world: world,
             perspectives: vec![],
@@ -23,15 +26,30 @@ impl View {
         }
     }
 
+    /// Caps the size of the global rayon thread pool used for perspective
+    /// scoring, so batch runs over many views stay predictable instead of
+    /// contending for every core at once. Must be called before any scoring
+    /// work, since rayon's global pool can only be configured once.
+    pub fn configure_thread_pool(num_threads: usize) {
+        ThreadPoolBuilder::new()
+            .num_threads(num_threads)
+            .build_global()
+            .expect("failed to configure rayon thread pool");
+    }
+
     pub fn add_perspectives(&mut self, perspectives: Vec<IpAddr>) {
-        for x in perspectives.iter() {
-            self.score_paths(&x);
+        let scored: Vec<Vec<(Path, IpAddr, u32)>> = perspectives
+            .par_iter()
+            .map(|addr| self.build_paths(addr))
+            .collect();
+        for paths in scored {
+            self.merge_paths(paths);
         }
         self.perspectives.extend(perspectives);
     }
 
     pub fn core_dissimilarity(&self, other: &View) -> Option<f64> {
-        if !Rc::ptr_eq(&self.world, &other.world) {
+        if !Arc::ptr_eq(&self.world, &other.world) {
             return None;
         }
         let mut total: f64 = 0.0;
@@ -54,7 +72,7 @@ impl View {
     }
 
     pub fn jaccard_dissimilarity(&self, other: &View) -> Option<f64> {
-        if !Rc::ptr_eq(&self.world, &other.world) {
+        if !Arc::ptr_eq(&self.world, &other.world) {
             return None;
         }
         let mut total: f64 = 0.0;
@@ -96,8 +114,57 @@ impl View {
         return total / (total_count as f64);
     }
 
+    /// Per-destination route diversity: the number of distinct near-minimal
+    /// valley-free paths (up to `k`) available from this view's perspectives,
+    /// keyed the same way as `hard_core`/`all_seen`. A destination reachable by
+    /// only one viable path scores 1; richer, more redundant routing scores
+    /// higher. Complements `core_dissimilarity`/`jaccard_dissimilarity`, which
+    /// compare diversity *across* views rather than measuring it directly.
+    pub fn route_diversity(&self, k: usize) -> HashMap<(IpAddr, u32), usize> {
+        let mut result = HashMap::new();
+        for addr in self.perspectives.iter() {
+            for (key, diversity) in self.route_diversity_for(addr, k) {
+                let entry = result.entry(key).or_insert(0);
+                if diversity > *entry {
+                    *entry = diversity;
+                }
+            }
+        }
+        result
+    }
+
+    fn route_diversity_for(&self, addr: &IpAddr, k: usize) -> Vec<((IpAddr, u32), usize)> {
+        let mut result = vec![];
+        let lookup = match addr {
+            IpAddr::V4(v4) => self.world.paths_v4.longest_match(*v4).map(|x| x.2),
+            IpAddr::V6(v6) => self.world.paths_v6.longest_match(*v6).map(|x| x.2),
+        };
+        if let Some(source_known_paths_in) = lookup {
+            if let Some(src_asn) = View::representative_origin(source_known_paths_in) {
+                for ((dest_addr, prefix), _) in self.world.destination_counts.iter() {
+                    let dest_lookup = match dest_addr {
+                        IpAddr::V4(v4) => self.world.paths_v4.exact_match(*v4, *prefix),
+                        IpAddr::V6(v6) => self.world.paths_v6.exact_match(*v6, *prefix),
+                    };
+                    if let Some(dest_known_paths_in) = dest_lookup {
+                        if let Some(dst_asn) = View::representative_destination(dest_known_paths_in) {
+                            let diversity = self.world.k_shortest_paths(src_asn, dst_asn, k).len();
+                            result.push(((*dest_addr, *prefix), diversity));
+                        }
+                    }
+                }
+            }
+        }
+        result
+    }
+
     fn score_paths(&mut self, addr: &IpAddr) {
-        for (path, ip, prefix) in self.build_paths(addr) {
+        let paths = self.build_paths(addr);
+        self.merge_paths(paths);
+    }
+
+    fn merge_paths(&mut self, paths: Vec<(Path, IpAddr, u32)>) {
+        for (path, ip, prefix) in paths {
             let mut value = HashSet::new();
             value.extend(path.path.clone());
             match self.hard_core.entry((ip, prefix)) {
@@ -122,27 +189,54 @@ impl View {
     }
 
     fn build_paths(&self, addr: &IpAddr) -> Vec<(Path, IpAddr, u32)> {
-        let mut result = vec![];
         let lookup = match addr {
             IpAddr::V4(v4) => self.world.paths_v4.longest_match(*v4).map(|x| x.2),
             IpAddr::V6(v6) => self.world.paths_v6.longest_match(*v6).map(|x| x.2),
         };
-        if let Some(source_known_paths_in) = lookup {
-            for ((dest_addr, prefix), _) in self.world.destination_counts.iter() {
+        let source_known_paths_in = match lookup {
+            Some(paths) => paths,
+            None => return vec![],
+        };
+        let src_asn = View::representative_origin(source_known_paths_in);
+        self.world
+            .destination_counts
+            .par_iter()
+            .filter_map(|((dest_addr, prefix), _)| {
                 let dest_lookup = match dest_addr {
                     IpAddr::V4(v4) => self.world.paths_v4.exact_match(*v4, *prefix),
                     IpAddr::V6(v6) => self.world.paths_v6.exact_match(*v6, *prefix),
                 };
-                if let Some(dest_known_paths_in) = dest_lookup {
-                    if let Some(shortest) =
-                        View::shortest_path(source_known_paths_in, dest_known_paths_in, &self.world)
-                    {
-                        result.push((shortest, *dest_addr, *prefix));
-                    }
-                }
-            }
-        }
-        return result;
+                let dest_known_paths_in = dest_lookup?;
+                let dst_asn = View::representative_destination(dest_known_paths_in);
+                let inferred = src_asn
+                    .zip(dst_asn)
+                    .and_then(|(s, d)| self.world.infer_path(s, d));
+                let chosen = inferred.or_else(|| {
+                    View::shortest_path(source_known_paths_in, dest_known_paths_in, &self.world)
+                });
+                chosen.map(|path| (path, *dest_addr, *prefix))
+            })
+            .collect()
+    }
+
+    /// The AS a perspective's observed paths are seen entering from, used as the
+    /// source node for graph-based path inference. Per `intersect_paths`'s
+    /// convention, a path's *own* ASN is its last element (the first element is
+    /// just whichever AS the recording collector happened to be adjacent to),
+    /// so this must match `representative_destination` and use `.last()`, not
+    /// `.first()`. Picked deterministically (the minimum ASN) rather than via
+    /// `HashSet` iteration order, which is randomly seeded per process and
+    /// would make inferred paths - and the `route_diversity` numbers built on
+    /// them - non-reproducible across runs over identical input data.
+    fn representative_origin(paths: &HashSet<Path>) -> Option<ASN> {
+        paths.iter().filter_map(|p| p.path.last().copied()).min()
+    }
+
+    /// The origin AS of a destination prefix's observed paths, used as the
+    /// target node for graph-based path inference. Deterministic for the same
+    /// reason as `representative_origin`.
+    fn representative_destination(paths: &HashSet<Path>) -> Option<ASN> {
+        paths.iter().filter_map(|p| p.path.last().copied()).min()
     }
 
     fn shortest_path(
@@ -213,3 +307,70 @@ impl View {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::BTreeSet;
+    use std::net::Ipv4Addr;
+    use treebitmap::IpLookupTable;
+    use world::ASRelation;
+
+    /// A two-hop peer-peer graph (100 <-> 200 <-> 300) plus one observed
+    /// prefix per endpoint, each recorded as `[collector-adjacent ASN,
+    /// prefix-owning ASN]` - mirroring real AS_PATHs, where the *last* ASN is
+    /// the one that actually originates the prefix.
+    fn peer_world() -> World {
+        let mut as_relationships = HashMap::new();
+        as_relationships.insert((100, 200), ASRelation::Peers);
+        as_relationships.insert((200, 100), ASRelation::Peers);
+        as_relationships.insert((200, 300), ASRelation::Peers);
+        as_relationships.insert((300, 200), ASRelation::Peers);
+        let mut adjacency: HashMap<ASN, Vec<(ASN, ASRelation)>> = HashMap::new();
+        for (&(a, b), &relation) in as_relationships.iter() {
+            adjacency.entry(a).or_insert_with(Vec::new).push((b, relation));
+        }
+
+        let mut paths_v4 = IpLookupTable::new();
+        let mut own_paths = HashSet::new();
+        own_paths.insert(Path { path: vec![999, 100] });
+        paths_v4.insert(Ipv4Addr::new(10, 0, 0, 0), 24, own_paths);
+        let mut dest_paths = HashSet::new();
+        dest_paths.insert(Path { path: vec![888, 300] });
+        paths_v4.insert(Ipv4Addr::new(10, 0, 1, 0), 24, dest_paths);
+
+        let mut destination_counts = HashMap::new();
+        destination_counts.insert((IpAddr::V4(Ipv4Addr::new(10, 0, 1, 0)), 24), 1);
+
+        World {
+            as_relationships,
+            adjacency,
+            paths_v4,
+            paths_v6: IpLookupTable::new(),
+            destination_counts,
+            known_asns: BTreeSet::new(),
+        }
+    }
+
+    #[test]
+    fn build_paths_infers_from_perspectives_own_asn_not_collector_adjacent_asn() {
+        let world = Arc::new(peer_world());
+        let view = View::new(world);
+        let addr = IpAddr::V4(Ipv4Addr::new(10, 0, 0, 1));
+
+        let result = view.build_paths(&addr);
+
+        // If representative_origin picked the collector-adjacent ASN (999)
+        // instead of the perspective's own ASN (100), 999 wouldn't be in the
+        // relationship graph, infer_path would return None, and the
+        // observed-path fallback (intersect_paths) would find no branching
+        // point between [999, 100] and [888, 300] either - so this entry
+        // would be silently dropped instead of resolving to the true,
+        // graph-inferred path.
+        assert_eq!(result.len(), 1);
+        let (path, dest_addr, prefix) = &result[0];
+        assert_eq!(*dest_addr, IpAddr::V4(Ipv4Addr::new(10, 0, 1, 0)));
+        assert_eq!(*prefix, 24);
+        assert_eq!(path.path, vec![100, 200, 300]);
+    }
+}
@@ -1,29 +1,114 @@
+extern crate bincode;
+extern crate sha3;
 extern crate treebitmap;
 
-use std::cmp::Ordering;
-use std::collections::{BTreeSet, HashMap, HashSet, VecDeque};
+use std::cmp::{Ordering, Reverse};
+use std::collections::{BTreeSet, BinaryHeap, HashMap, HashSet, VecDeque};
 use std::fs::File;
-use std::io::{BufRead, BufReader};
+use std::io::{BufRead, BufReader, Read, Write};
 use std::net::IpAddr::{V4, V6};
 use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
 use std::str::FromStr;
 
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use sha3::{Digest, Sha3_256};
 use treebitmap::IpLookupTable;
 
+use mrt;
+
+/// Bumped whenever the on-disk cache layout changes, so stale caches from an
+/// older binary are rebuilt instead of misread.
+const CACHE_FORMAT_VERSION: u8 = 1;
+const CACHE_DIGEST_LEN: usize = 32;
+
 pub struct World {
     pub(crate) as_relationships: HashMap<(ASN, ASN), ASRelation>,
+    /// Adjacency view of `as_relationships`, built once so the per-destination
+    /// Dijkstra searches in `constrained_shortest_path` don't re-derive it from
+    /// every relationship edge on every call.
+    pub(crate) adjacency: HashMap<ASN, Vec<(ASN, ASRelation)>>,
     pub(crate) paths_v4: IpLookupTable<Ipv4Addr, HashSet<Path>>,
     pub(crate) paths_v6: IpLookupTable<Ipv6Addr, HashSet<Path>>,
     pub(crate) destination_counts: HashMap<(IpAddr, u32), u64>,
     pub(crate) known_asns: BTreeSet<ASN>,
 }
 
-#[derive(Hash, PartialEq, Eq, Clone, Debug)]
+fn build_adjacency(as_relationships: &HashMap<(ASN, ASN), ASRelation>) -> HashMap<ASN, Vec<(ASN, ASRelation)>> {
+    let mut adjacency: HashMap<ASN, Vec<(ASN, ASRelation)>> = HashMap::new();
+    for (&(a, b), &relation) in as_relationships.iter() {
+        adjacency.entry(a).or_insert_with(Vec::new).push((b, relation));
+    }
+    adjacency
+}
+
+/// Flat, serde-friendly stand-in for `World`: `IpLookupTable` isn't
+/// serializable, so the lookup tables are flattened into entry lists and
+/// rebuilt on load.
+#[derive(Serialize, Deserialize)]
+struct WorldShadow {
+    as_relationships: HashMap<(ASN, ASN), ASRelation>,
+    paths_v4: Vec<(Ipv4Addr, u32, HashSet<Path>)>,
+    paths_v6: Vec<(Ipv6Addr, u32, HashSet<Path>)>,
+    destination_counts: HashMap<(IpAddr, u32), u64>,
+    known_asns: BTreeSet<ASN>,
+}
+
+impl Serialize for World {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        let shadow = WorldShadow {
+            as_relationships: self.as_relationships.clone(),
+            paths_v4: self
+                .paths_v4
+                .iter()
+                .map(|(addr, mask, set)| (addr, mask, set.clone()))
+                .collect(),
+            paths_v6: self
+                .paths_v6
+                .iter()
+                .map(|(addr, mask, set)| (addr, mask, set.clone()))
+                .collect(),
+            destination_counts: self.destination_counts.clone(),
+            known_asns: self.known_asns.clone(),
+        };
+        shadow.serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for World {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let shadow = WorldShadow::deserialize(deserializer)?;
+        let mut paths_v4 = IpLookupTable::new();
+        for (addr, mask, set) in shadow.paths_v4 {
+            paths_v4.insert(addr, mask, set);
+        }
+        let mut paths_v6 = IpLookupTable::new();
+        for (addr, mask, set) in shadow.paths_v6 {
+            paths_v6.insert(addr, mask, set);
+        }
+        let adjacency = build_adjacency(&shadow.as_relationships);
+        Ok(World {
+            as_relationships: shadow.as_relationships,
+            adjacency,
+            paths_v4,
+            paths_v6,
+            destination_counts: shadow.destination_counts,
+            known_asns: shadow.known_asns,
+        })
+    }
+}
+
+#[derive(Hash, PartialEq, Eq, Clone, Debug, Serialize, Deserialize)]
 pub struct Path {
     pub(crate) path: Vec<ASN>,
 }
 
-#[derive(Debug, PartialEq, Eq, Hash, Copy, Clone, PartialOrd)]
+#[derive(Debug, PartialEq, Eq, Hash, Copy, Clone, PartialOrd, Ord, Serialize, Deserialize)]
 pub(crate) enum ASRelation {
     No,
     Consumes,
@@ -41,6 +126,7 @@ impl World {
     ) -> World {
         let mut result = World {
             as_relationships: HashMap::new(),
+            adjacency: HashMap::new(),
             paths_v4: IpLookupTable::new(),
             paths_v6: IpLookupTable::new(),
             destination_counts: HashMap::new(),
@@ -52,6 +138,61 @@ impl World {
         result
     }
 
+    /// Like `build_new`, but caches the parsed result at `cache_path`. The
+    /// cache is keyed on a SHA3-256 digest of the three input files' contents
+    /// plus `CACHE_FORMAT_VERSION`, so editing any input (or bumping
+    /// `CACHE_FORMAT_VERSION`) transparently rebuilds it instead of silently
+    /// reusing stale data; a new binary with an unchanged version reuses it. Turns repeated experiments from minutes of parsing into a
+    /// sub-second load.
+    pub fn build_or_load(
+        cache_path: &str,
+        as_relationship_file: &str,
+        bgp_data_file: &str,
+        destination_file: &str,
+    ) -> World {
+        let digest = World::digest_inputs(as_relationship_file, bgp_data_file, destination_file);
+        if let Some(world) = World::load_cache(cache_path, &digest) {
+            return world;
+        }
+        let world = World::build_new(as_relationship_file, bgp_data_file, destination_file);
+        world.write_cache(cache_path, &digest);
+        world
+    }
+
+    fn digest_inputs(
+        as_relationship_file: &str,
+        bgp_data_file: &str,
+        destination_file: &str,
+    ) -> [u8; CACHE_DIGEST_LEN] {
+        let mut hasher = Sha3_256::new();
+        for fname in [as_relationship_file, bgp_data_file, destination_file].iter() {
+            let mut f = File::open(fname).expect("file not found");
+            let mut contents = Vec::new();
+            f.read_to_end(&mut contents).expect("failed to read file");
+            hasher.update(&contents);
+        }
+        hasher.update(&[CACHE_FORMAT_VERSION]);
+        let mut digest = [0u8; CACHE_DIGEST_LEN];
+        digest.copy_from_slice(hasher.finalize().as_slice());
+        digest
+    }
+
+    fn load_cache(cache_path: &str, digest: &[u8; CACHE_DIGEST_LEN]) -> Option<World> {
+        let mut f = File::open(cache_path).ok()?;
+        let mut stored_digest = [0u8; CACHE_DIGEST_LEN];
+        f.read_exact(&mut stored_digest).ok()?;
+        if &stored_digest != digest {
+            return None;
+        }
+        bincode::deserialize_from(f).ok()
+    }
+
+    fn write_cache(&self, cache_path: &str, digest: &[u8; CACHE_DIGEST_LEN]) {
+        let mut f = File::create(cache_path).expect("failed to create cache file");
+        f.write_all(digest).expect("failed to write cache header");
+        bincode::serialize_into(f, self).expect("failed to serialize world");
+    }
+
     fn load_relationships(&mut self, fname: &str) {
         let mut result = HashMap::new();
         let f = File::open(fname).expect("file not found");
@@ -71,10 +212,39 @@ impl World {
                 result.insert((b, a), ASRelation::Peers);
             }
         }
+        self.adjacency = build_adjacency(&result);
         self.as_relationships = result
     }
 
     fn load_bgp_data(&mut self, fname: &str) {
+        if mrt::looks_like_mrt(fname) {
+            self.load_bgp_data_mrt(fname);
+            return;
+        }
+        self.load_bgp_data_text(fname);
+    }
+
+    /// Parses a binary MRT TABLE_DUMP_V2 dump (e.g. straight off
+    /// RouteViews/RIPE RIS) without requiring an external text conversion.
+    fn load_bgp_data_mrt(&mut self, fname: &str) {
+        for ((addr, prefix_length), set) in mrt::parse_file(fname) {
+            for path in set.iter() {
+                for asn in path.path.iter() {
+                    self.known_asns.insert(*asn);
+                }
+            }
+            match addr {
+                V4(v4) => {
+                    self.paths_v4.insert(v4, prefix_length, set);
+                }
+                V6(v6) => {
+                    self.paths_v6.insert(v6, prefix_length, set);
+                }
+            }
+        }
+    }
+
+    fn load_bgp_data_text(&mut self, fname: &str) {
         let mut known: HashMap<String, HashSet<Path>> = HashMap::new();
         let f = File::open(fname).expect("file not found");
         for line in BufReader::new(f).lines().map(|x| x.unwrap()) {
@@ -109,6 +279,157 @@ impl World {
         }
     }
 
+    /// Finds the shortest valley-free AS-path between `src_asn` and `dst_asn` by
+    /// running a constrained Dijkstra over the AS-relationship graph, rather than
+    /// stitching together two observed BGP paths. Falls back to `None` when no
+    /// Gao-Rexford-valid route exists in the known relationship graph.
+    pub fn infer_path(&self, src_asn: ASN, dst_asn: ASN) -> Option<Path> {
+        if src_asn == dst_asn {
+            return Some(Path {
+                path: vec![src_asn],
+            });
+        }
+        self.constrained_shortest_path(src_asn, dst_asn, ASRelation::No, &HashSet::new(), &HashSet::new())
+    }
+
+    /// Core of `infer_path`, parameterized so k-shortest-path search can reuse it
+    /// with a starting relation phase, a set of banned directed edges, and a set
+    /// of banned nodes (e.g. a Yen spur's root-path prefix, so the spur search
+    /// can't route back through a node already used to reach the spur node).
+    pub(crate) fn constrained_shortest_path(
+        &self,
+        src_asn: ASN,
+        dst_asn: ASN,
+        start_phase: ASRelation,
+        banned_edges: &HashSet<(ASN, ASN)>,
+        banned_nodes: &HashSet<ASN>,
+    ) -> Option<Path> {
+        let start = (src_asn, start_phase);
+        let mut dist: HashMap<(ASN, ASRelation), usize> = HashMap::new();
+        let mut prev: HashMap<(ASN, ASRelation), (ASN, ASRelation)> = HashMap::new();
+        let mut heap = BinaryHeap::new();
+        dist.insert(start, 0);
+        heap.push(Reverse((0usize, src_asn, start_phase)));
+
+        let mut goal = None;
+        while let Some(Reverse((cost, asn, phase))) = heap.pop() {
+            if asn == dst_asn {
+                goal = Some((asn, phase));
+                break;
+            }
+            if dist.get(&(asn, phase)).map_or(true, |&best| cost > best) {
+                continue;
+            }
+            if let Some(neighbors) = self.adjacency.get(&asn) {
+                for &(next_asn, relation) in neighbors.iter() {
+                    if relation < phase
+                        || banned_edges.contains(&(asn, next_asn))
+                        || banned_nodes.contains(&next_asn)
+                    {
+                        continue;
+                    }
+                    let next_state = (next_asn, relation);
+                    let next_cost = cost + 1;
+                    if dist.get(&next_state).map_or(true, |&best| next_cost < best) {
+                        dist.insert(next_state, next_cost);
+                        prev.insert(next_state, (asn, phase));
+                        heap.push(Reverse((next_cost, next_asn, relation)));
+                    }
+                }
+            }
+        }
+
+        let (mut asn, mut phase) = goal?;
+        let mut path = vec![asn];
+        while (asn, phase) != start {
+            let (prev_asn, prev_phase) = prev[&(asn, phase)];
+            path.push(prev_asn);
+            asn = prev_asn;
+            phase = prev_phase;
+        }
+        path.reverse();
+        Some(Path { path })
+    }
+
+    /// Computes up to `k` distinct near-minimal valley-free paths between
+    /// `src_asn` and `dst_asn` using Yen's algorithm, built on top of the
+    /// policy-aware Dijkstra used by `infer_path`. The result is ordered
+    /// shortest-first and may contain fewer than `k` entries if the
+    /// relationship graph doesn't support that many loop-free alternatives.
+    pub fn k_shortest_paths(&self, src_asn: ASN, dst_asn: ASN, k: usize) -> Vec<Path> {
+        let mut found: Vec<Path> = vec![];
+        if k == 0 {
+            return found;
+        }
+        let first = match self.infer_path(src_asn, dst_asn) {
+            Some(path) => path,
+            None => return found,
+        };
+        found.push(first);
+
+        let mut candidates: BinaryHeap<Reverse<Path>> = BinaryHeap::new();
+        // Tracks every path already in `found` or already sitting in
+        // `candidates`, so a candidate reconstructed from two different spur
+        // nodes (in this round or a later one, since ties are common when
+        // every edge costs 1 hop) is only ever queued once.
+        let mut queued: HashSet<Path> = found.iter().cloned().collect();
+        while found.len() < k {
+            let prev_path = found.last().unwrap().clone();
+            for spur_index in 0..prev_path.path.len().saturating_sub(1) {
+                let spur_node = prev_path.path[spur_index];
+                let root_path = &prev_path.path[0..=spur_index];
+
+                let mut banned_edges: HashSet<(ASN, ASN)> = HashSet::new();
+                for accepted in found.iter() {
+                    if accepted.path.len() > spur_index
+                        && &accepted.path[0..=spur_index] == root_path
+                        && accepted.path.len() > spur_index + 1
+                    {
+                        banned_edges.insert((accepted.path[spur_index], accepted.path[spur_index + 1]));
+                    }
+                }
+                // Every node already used to reach the spur node is off-limits for
+                // the spur search, or the reassembled candidate could revisit it
+                // and produce an AS-path loop that can't occur in real BGP.
+                let banned_nodes: HashSet<ASN> = root_path[..spur_index].iter().cloned().collect();
+
+                let spur_phase = World::phase_after(&self.as_relationships, &root_path[..spur_index + 1]);
+                if let Some(spur_path) = self.constrained_shortest_path(
+                    spur_node,
+                    dst_asn,
+                    spur_phase,
+                    &banned_edges,
+                    &banned_nodes,
+                ) {
+                    let mut total = root_path[..spur_index].to_vec();
+                    total.extend(spur_path.path);
+                    let candidate = Path { path: total };
+                    if queued.insert(candidate.clone()) {
+                        candidates.push(Reverse(candidate));
+                    }
+                }
+            }
+
+            match candidates.pop() {
+                Some(Reverse(next)) => found.push(next),
+                None => break,
+            }
+        }
+        found
+    }
+
+    /// The relation phase a valley-free path is in after following `path`'s
+    /// edges, mirroring the monotonicity check in `Path::valleyless`.
+    fn phase_after(as_relationships: &HashMap<(ASN, ASN), ASRelation>, path: &[ASN]) -> ASRelation {
+        let mut phase = ASRelation::No;
+        for window in path.windows(2) {
+            if let Some(&relation) = as_relationships.get(&(window[0], window[1])) {
+                phase = relation;
+            }
+        }
+        phase
+    }
+
     fn load_destinations(&mut self, fname: &str) {
         let f = File::open(fname).expect("file not found");
         for line in BufReader::new(f).lines().map(|x| x.unwrap()) {
@@ -211,3 +532,48 @@ impl PartialOrd for Path {
         Some(self.cmp(other))
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A "double diamond" (S-{A,B}-M-{C,D}-T, all peer links) where the M
+    /// bottleneck means every S-T route is exactly 4 hops - a genuine tie
+    /// with several equal-length candidates, the scenario where
+    /// `k_shortest_paths` previously let the same reconstructed path get
+    /// queued twice (once per spur node that happened to derive it).
+    fn double_diamond() -> World {
+        const S: ASN = 1;
+        const A: ASN = 2;
+        const B: ASN = 3;
+        const M: ASN = 4;
+        const C: ASN = 5;
+        const D: ASN = 6;
+        const T: ASN = 7;
+        let edges = [(S, A), (S, B), (A, M), (B, M), (M, C), (M, D), (C, T), (D, T)];
+        let mut as_relationships = HashMap::new();
+        for &(a, b) in edges.iter() {
+            as_relationships.insert((a, b), ASRelation::Peers);
+            as_relationships.insert((b, a), ASRelation::Peers);
+        }
+        let adjacency = build_adjacency(&as_relationships);
+        World {
+            as_relationships,
+            adjacency,
+            paths_v4: IpLookupTable::new(),
+            paths_v6: IpLookupTable::new(),
+            destination_counts: HashMap::new(),
+            known_asns: BTreeSet::new(),
+        }
+    }
+
+    #[test]
+    fn k_shortest_paths_never_returns_duplicate_paths() {
+        let world = double_diamond();
+        let paths = world.k_shortest_paths(1, 7, 10);
+
+        assert!(paths.len() >= 2, "expected multiple tied routes through the bottleneck");
+        let unique: HashSet<&Path> = paths.iter().collect();
+        assert_eq!(unique.len(), paths.len(), "k_shortest_paths returned a duplicate path: {:?}", paths);
+    }
+}